@@ -1,8 +1,8 @@
 #![feature(ptr_internals, alloc)]
-use core::ptr::{self, Unique};
+use core::ptr::{self, NonNull, Unique};
 use std::alloc::{alloc, dealloc, realloc, Layout};
 use std::mem;
-use std::ops::{Deref, DerefMut};
+use std::ops::{Bound, Deref, DerefMut, RangeBounds};
 
 struct RawValIter<T> {
     start: *const T,
@@ -17,7 +17,12 @@ impl<T> RawValIter<T> {
     unsafe fn new(slice: &[T]) -> Self {
         RawValIter {
             start: slice.as_ptr(),
-            end: if slice.len() == 0 {
+            end: if mem::size_of::<T>() == 0 {
+                // a pointer offset of a ZST is a no-op, so an offset-based
+                // `end` would never differ from `start`. Do the arithmetic
+                // on the address as a `usize` instead.
+                ((slice.as_ptr() as usize) + slice.len()) as *const _
+            } else if slice.len() == 0 {
                 // if `len = 0`, then this is not actually allocated memory.
                 // Need to avoid offsetting because that will give wrong
                 // information to LLVM via GEP.
@@ -29,76 +34,371 @@ impl<T> RawValIter<T> {
     }
 }
 
-pub struct Vec<T> {
+impl<T> RawValIter<T> {
+    fn len(&self) -> usize {
+        let elem_size = mem::size_of::<T>();
+        (self.end as usize - self.start as usize) / if elem_size == 0 { 1 } else { elem_size }
+    }
+}
+
+impl<T> Iterator for RawValIter<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                self.end = if mem::size_of::<T>() == 0 {
+                    (self.end as usize - 1) as *const _
+                } else {
+                    self.end.offset(-1)
+                };
+                Some(ptr::read(self.end))
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.len();
+        (len, Some(len))
+    }
+}
+
+impl<T> DoubleEndedIterator for RawValIter<T> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.start == self.end {
+            None
+        } else {
+            unsafe {
+                let old_start = self.start;
+                self.start = if mem::size_of::<T>() == 0 {
+                    (self.start as usize + 1) as *const _
+                } else {
+                    self.start.offset(1)
+                };
+                Some(ptr::read(old_start))
+            }
+        }
+    }
+}
+
+/// Indicates that an `Allocator` could not fulfil an allocation or growth
+/// request (e.g. the underlying allocator returned a null pointer).
+#[derive(Debug)]
+pub struct AllocError;
+
+/// A source of memory for `Vec` (and friends) to allocate from, so that an
+/// arena, a bump allocator, or a pool can be used in place of the global
+/// allocator.
+pub trait Allocator {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError>;
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError>;
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The default `Allocator`, backed by `std::alloc`'s global allocator.
+#[derive(Clone, Copy, Default)]
+pub struct Global;
+
+impl Allocator for Global {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        unsafe { NonNull::new(alloc(layout)).ok_or(AllocError) }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        NonNull::new(realloc(ptr.as_ptr(), old_layout, new_layout.size())).ok_or(AllocError)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        dealloc(ptr.as_ptr(), layout);
+    }
+}
+
+impl<A: Allocator + ?Sized> Allocator for &A {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+        (**self).allocate(layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, AllocError> {
+        (**self).grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        (**self).deallocate(ptr, layout)
+    }
+}
+
+// RawVec owns the `(ptr, cap)` pair and knows how to grow and free the
+// backing allocation, but has no idea how many of its slots are
+// initialized. That's `Vec`'s job (and `IntoIter`'s).
+struct RawVec<T, A: Allocator = Global> {
     ptr: Unique<T>,
     cap: usize,
+    alloc: A,
+}
+
+impl<T> RawVec<T, Global> {
+    fn new() -> Self {
+        RawVec::new_in(Global)
+    }
+
+    fn with_capacity(cap: usize) -> Self {
+        RawVec::with_capacity_in(cap, Global)
+    }
+}
+
+impl<T, A: Allocator> RawVec<T, A> {
+    fn new_in(alloc: A) -> Self {
+        // !0 is usize::MAX. This branch should be stripped at compile time.
+        let cap = if mem::size_of::<T>() == 0 { !0 } else { 0 };
+
+        // Unique::empty() doubles as "unallocated" and "zero-sized allocation"
+        RawVec {
+            ptr: Unique::empty(),
+            cap,
+            alloc,
+        }
+    }
+
+    fn with_capacity_in(cap: usize, alloc: A) -> Self {
+        let mut buf = RawVec::new_in(alloc);
+        if cap > 0 {
+            buf.realloc_to(cap).unwrap();
+        }
+        buf
+    }
+
+    fn as_byte_ptr(&self) -> NonNull<u8> {
+        unsafe { NonNull::new_unchecked(self.ptr.as_ptr() as *mut u8) }
+    }
+
+    // reallocates to hold exactly `new_cap` elements, using `Layout::array`
+    // to get the size-and-overflow checking for free instead of hand-rolling
+    // the byte math ourselves. `new_cap == 0` frees the allocation entirely.
+    fn realloc_to(&mut self, new_cap: usize) -> Result<(), TryReserveError> {
+        if mem::size_of::<T>() == 0 {
+            // ZSTs never allocate; `cap` is already effectively infinite.
+            return Ok(());
+        }
+
+        if new_cap == 0 {
+            if self.cap != 0 {
+                unsafe {
+                    let old_layout = Layout::array::<T>(self.cap).unwrap();
+                    self.alloc.deallocate(self.as_byte_ptr(), old_layout);
+                }
+                self.ptr = Unique::empty();
+                self.cap = 0;
+            }
+            return Ok(());
+        }
+
+        let new_layout = Layout::array::<T>(new_cap).map_err(|_| TryReserveError::CapacityOverflow)?;
+
+        let result = if self.cap == 0 {
+            self.alloc.allocate(new_layout)
+        } else {
+            let old_layout = Layout::array::<T>(self.cap).unwrap();
+            unsafe { self.alloc.grow(self.as_byte_ptr(), old_layout, new_layout) }
+        };
+
+        let ptr = result.map_err(|_| TryReserveError::AllocError { layout: new_layout })?;
+
+        unsafe {
+            self.ptr = Unique::new_unchecked(ptr.as_ptr() as *mut _);
+        }
+        self.cap = new_cap;
+        Ok(())
+    }
+
+    // doubles the capacity, returning a `TryReserveError` on OOM or overflow
+    // (the infallible `grow` below panics on that error); `try_reserve_exact`
+    // below is used when a caller wants a specific capacity instead of the
+    // next doubling.
+    fn grow_amortized(&mut self) -> Result<(), TryReserveError> {
+        // since we set the capacity to usize::MAX when elem_size is 0,
+        // getting to here necessarily means the Vec is overfull.
+        assert!(mem::size_of::<T>() != 0, "capacity overflow");
+
+        let new_cap = if self.cap == 0 { 1 } else { self.cap * 2 };
+        self.realloc_to(new_cap)
+    }
+
+    fn grow(&mut self) {
+        self.grow_amortized().unwrap();
+    }
+
+    // grows to exactly `used_cap + additional` if the current capacity is
+    // smaller (it never shrinks), without the "we'll OOM first" guarantee:
+    // callers get a `TryReserveError` back instead of an abort.
+    fn try_reserve_exact(&mut self, used_cap: usize, additional: usize) -> Result<(), TryReserveError> {
+        let needed_cap = used_cap
+            .checked_add(additional)
+            .ok_or(TryReserveError::CapacityOverflow)?;
+
+        if needed_cap <= self.cap {
+            return Ok(());
+        }
+
+        self.realloc_to(needed_cap)
+    }
+
+    // amortized-doubling reserve: grows to `max(cap * 2, used_cap + additional)`
+    // so that repeated small `reserve` calls don't each reallocate.
+    fn reserve(&mut self, used_cap: usize, additional: usize) {
+        let needed_cap = used_cap.checked_add(additional).expect("capacity overflow");
+        if needed_cap <= self.cap {
+            return;
+        }
+
+        let new_cap = ::std::cmp::max(self.cap * 2, needed_cap);
+        self.realloc_to(new_cap).unwrap();
+    }
+
+    fn shrink_to_fit(&mut self, used_cap: usize) {
+        if used_cap < self.cap {
+            self.realloc_to(used_cap).unwrap();
+        }
+    }
+}
+
+/// Error returned by the fallible allocation APIs (`Vec::try_reserve`,
+/// `Vec::try_push`) when the requested capacity can't be satisfied.
+#[derive(Debug)]
+pub enum TryReserveError {
+    /// The requested capacity overflowed `usize` or exceeded `isize::MAX` bytes.
+    CapacityOverflow,
+    /// The allocator returned a null pointer for this `Layout`.
+    AllocError { layout: Layout },
+}
+
+impl<T, A: Allocator> Drop for RawVec<T, A> {
+    fn drop(&mut self) {
+        if self.cap != 0 && mem::size_of::<T>() != 0 {
+            unsafe {
+                let layout = Layout::array::<T>(self.cap).unwrap();
+                self.alloc.deallocate(self.as_byte_ptr(), layout);
+            }
+        }
+    }
+}
+
+pub struct Vec<T, A: Allocator = Global> {
+    buf: RawVec<T, A>,
     len: usize,
 }
 
-impl<T> Vec<T> {
+impl<T> Vec<T, Global> {
     fn new() -> Self {
-        assert!(mem::size_of::<T>() != 0, "We're not ready to handle ZSTs");
         Vec {
-            ptr: Unique::empty(),
+            buf: RawVec::new(),
             len: 0,
-            cap: 0,
         }
     }
-    fn grow(&mut self) {
-        // this is all pretty delicate, so let's say it's all unsafe
-        unsafe {
-            let (new_cap, ptr) = if self.cap == 0 {
-                let ptr = alloc(Layout::new::<T>());
-                (1, ptr)
-            } else {
-                let elem_size = mem::size_of::<T>();
-                // as an invariant, we can assume that `self.cap < isize::MAX`,
-                // so this doesn't need to be checked.
-                let new_cap = self.cap * 2;
-                // Similarly this can't overflow due to previously allocating this
-                let old_num_bytes = self.cap * elem_size;
-
-                // check that the new allocation doesn't exceed `isize::MAX` at all
-                // regardless of the actual size of the capacity. This combines the
-                // `new_cap <= isize::MAX` and `new_num_bytes <= usize::MAX` checks
-                // we need to make. We lose the ability to allocate e.g. 2/3rds of
-                // the address space with a single Vec of i16's on 32-bit though.
-                // Alas, poor Yorick -- I knew him, Horatio.
-                assert!(
-                    old_num_bytes <= (::std::isize::MAX as usize) / 2,
-                    "capacity overflow"
-                );
 
-                let new_num_bytes = old_num_bytes * 2;
-                let layout = Layout::from_size_align_unchecked(old_num_bytes, mem::align_of::<T>());
-                let ptr = realloc(self.ptr.as_ptr() as *mut _, layout, new_num_bytes);
-                (new_cap, ptr)
-            };
+    pub fn with_capacity(cap: usize) -> Self {
+        Vec {
+            buf: RawVec::with_capacity(cap),
+            len: 0,
+        }
+    }
+}
+
+impl<T, A: Allocator> Vec<T, A> {
+    pub fn new_in(alloc: A) -> Self {
+        Vec {
+            buf: RawVec::new_in(alloc),
+            len: 0,
+        }
+    }
 
-            self.ptr = Unique::new_unchecked(ptr as *mut _);
-            self.cap = new_cap;
+    pub fn with_capacity_in(cap: usize, alloc: A) -> Self {
+        Vec {
+            buf: RawVec::with_capacity_in(cap, alloc),
+            len: 0,
         }
     }
 
+    pub fn allocator(&self) -> &A {
+        &self.buf.alloc
+    }
+
+    fn ptr(&self) -> *mut T {
+        self.buf.ptr.as_ptr()
+    }
+
+    fn cap(&self) -> usize {
+        self.buf.cap
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.cap()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.buf.reserve(self.len, additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.buf.shrink_to_fit(self.len);
+    }
+
+    fn grow(&mut self) {
+        self.buf.grow();
+    }
+
     pub fn push(&mut self, elem: T) {
-        if self.len == self.cap {
+        if self.len == self.cap() {
             self.grow();
         }
 
         unsafe {
-            ptr::write(self.ptr.as_ptr().offset(self.len as isize), elem);
+            ptr::write(self.ptr().offset(self.len as isize), elem);
         }
 
         // Can't fail, we'll OOM first.
         self.len += 1;
     }
 
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.buf.try_reserve_exact(self.len, additional)
+    }
+
+    pub fn try_push(&mut self, elem: T) -> Result<(), TryReserveError> {
+        if self.len == self.cap() {
+            self.try_reserve(1)?;
+        }
+
+        unsafe {
+            ptr::write(self.ptr().offset(self.len as isize), elem);
+        }
+        self.len += 1;
+        Ok(())
+    }
+
     pub fn pop(&mut self) -> Option<T> {
         if self.len == 0 {
             None
         } else {
             self.len -= 1;
-            unsafe { Some(ptr::read(self.ptr.as_ptr().offset(self.len as isize))) }
+            unsafe { Some(ptr::read(self.ptr().offset(self.len as isize))) }
         }
     }
 
@@ -106,7 +406,7 @@ impl<T> Vec<T> {
         // Note: `<=` because it's valid to insert after everything
         // which would be equivalent to push.
         assert!(index <= self.len, "index out of bounds");
-        if self.cap == self.len {
+        if self.cap() == self.len {
             self.grow();
         }
 
@@ -114,12 +414,12 @@ impl<T> Vec<T> {
             if index < self.len {
                 // ptr::copy(src, dest, len): "copy from source to dest len elems"
                 ptr::copy(
-                    self.ptr.as_ptr().offset(index as isize),
-                    self.ptr.as_ptr().offset(index as isize + 1),
+                    self.ptr().offset(index as isize),
+                    self.ptr().offset(index as isize + 1),
                     self.len - index,
                 );
             }
-            ptr::write(self.ptr.as_ptr().offset(index as isize), elem);
+            ptr::write(self.ptr().offset(index as isize), elem);
             self.len += 1;
         }
     }
@@ -129,90 +429,287 @@ impl<T> Vec<T> {
         assert!(index < self.len, "index out of bounds");
         unsafe {
             self.len -= 1;
-            let result = ptr::read(self.ptr.as_ptr().offset(index as isize));
+            let result = ptr::read(self.ptr().offset(index as isize));
             ptr::copy(
-                self.ptr.as_ptr().offset(index as isize + 1),
-                self.ptr.as_ptr().offset(index as isize),
+                self.ptr().offset(index as isize + 1),
+                self.ptr().offset(index as isize),
                 self.len - index,
             );
             result
         }
     }
 
-    pub fn into_iter(self) -> IntoIter<T> {
-        IntoIter {
-            raw: unsafe { RawValIter::new(&self) },
-            vec: self,
+    pub fn into_iter(self) -> IntoIter<T, A> {
+        unsafe {
+            let iter = RawValIter::new(&self);
+            let buf = ptr::read(&self.buf);
+            mem::forget(self);
+
+            IntoIter { _buf: buf, iter }
         }
     }
-}
 
-impl<T> Drop for Vec<T> {
-    fn drop(&mut self) {
-        if self.cap != 0 {
-            while let Some(_) = self.pop() {}
+    pub fn drain<R: RangeBounds<usize>>(&mut self, range: R) -> Drain<T, A> {
+        let len = self.len;
+        let start = match range.start_bound() {
+            Bound::Included(&n) => n,
+            Bound::Excluded(&n) => n + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&n) => n + 1,
+            Bound::Excluded(&n) => n,
+            Bound::Unbounded => len,
+        };
 
-            let align = mem::align_of::<T>();
-            let elem_size = mem::size_of::<T>();
-            let num_bytes = elem_size * self.cap;
-            unsafe {
-                dealloc(
-                    self.ptr.as_ptr() as *mut _,
-                    Layout::from_size_align_unchecked(num_bytes, align),
-                );
+        assert!(start <= end, "start drain index is after end drain index");
+        assert!(end <= len, "end drain index out of bounds");
+
+        unsafe {
+            let range_slice = ::std::slice::from_raw_parts(self.ptr().offset(start as isize), end - start);
+
+            // set `len` up front so that if the `Drain` leaks, we keep the
+            // `Vec` in a safe (if truncated) state rather than double-dropping.
+            self.len = start;
+
+            Drain {
+                tail_start: end,
+                tail_len: len - end,
+                iter: RawValIter::new(range_slice),
+                vec: self as *mut _,
             }
         }
     }
+
+    pub fn extract_if<F: FnMut(&mut T) -> bool>(&mut self, pred: F) -> ExtractIf<T, F, A> {
+        let old_len = self.len;
+
+        // set `len` to 0 up front so that if `ExtractIf` leaks, we keep the
+        // `Vec` in a safe (if empty) state rather than leaving a hole.
+        self.len = 0;
+
+        ExtractIf {
+            vec: self as *mut _,
+            read: 0,
+            write: 0,
+            old_len,
+            pred,
+        }
+    }
 }
 
-impl<T> Deref for Vec<T> {
+impl<T, A: Allocator> Drop for Vec<T, A> {
+    fn drop(&mut self) {
+        while let Some(_) = self.pop() {}
+        // deallocation is handled by `RawVec`'s `Drop`
+    }
+}
+
+impl<T, A: Allocator> Deref for Vec<T, A> {
     type Target = [T];
     fn deref(&self) -> &[T] {
-        unsafe { ::std::slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+        unsafe { ::std::slice::from_raw_parts(self.ptr(), self.len) }
     }
 }
 
-impl<T> DerefMut for Vec<T> {
+impl<T, A: Allocator> DerefMut for Vec<T, A> {
     fn deref_mut(&mut self) -> &mut [T] {
-        unsafe { ::std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+        unsafe { ::std::slice::from_raw_parts_mut(self.ptr(), self.len) }
     }
 }
 
-pub struct IntoIter<T> {
-    vec: Vec<T>,
-    raw: RawValIter<T>,
+pub struct IntoIter<T, A: Allocator = Global> {
+    _buf: RawVec<T, A>, // we don't actually care about this, just need it to live
+    iter: RawValIter<T>,
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, A: Allocator> Iterator for IntoIter<T, A> {
     type Item = T;
     fn next(&mut self) -> Option<T> {
-        if self.raw.start == self.raw.end {
-            None
-        } else {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<T, A: Allocator> DoubleEndedIterator for IntoIter<T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back()
+    }
+}
+
+impl<T, A: Allocator> ExactSizeIterator for IntoIter<T, A> {}
+
+impl<T, A: Allocator> Drop for IntoIter<T, A> {
+    fn drop(&mut self) {
+        // drop any remaining elements; the allocation is freed by `_buf`
+        for _ in &mut *self {}
+    }
+}
+
+pub struct Drain<T, A: Allocator = Global> {
+    vec: *mut Vec<T, A>,
+    tail_start: usize,
+    tail_len: usize,
+    iter: RawValIter<T>,
+}
+
+impl<T, A: Allocator> Iterator for Drain<T, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        self.iter.next()
+    }
+}
+
+impl<T, A: Allocator> DoubleEndedIterator for Drain<T, A> {
+    fn next_back(&mut self) -> Option<T> {
+        self.iter.next_back()
+    }
+}
+
+impl<T, A: Allocator> Drain<T, A> {
+    // Keeps the unyielded elements in the `Vec` instead of dropping them,
+    // by shifting them down to directly follow the prefix kept before the
+    // drained range.
+    pub fn keep_rest(self) {
+        unsafe {
+            let source_vec = &mut *self.vec;
+            let start = source_vec.len;
+            let unyielded_ptr = self.iter.start;
+            let unyielded_len = self.iter.len();
+
+            ptr::copy(unyielded_ptr, source_vec.ptr().offset(start as isize), unyielded_len);
+
+            let new_tail_start = start + unyielded_len;
+            if self.tail_len > 0 && self.tail_start != new_tail_start {
+                ptr::copy(
+                    source_vec.ptr().offset(self.tail_start as isize),
+                    source_vec.ptr().offset(new_tail_start as isize),
+                    self.tail_len,
+                );
+            }
+
+            source_vec.len = new_tail_start + self.tail_len;
+        }
+
+        // skip our Drop impl, which would otherwise drop the elements we
+        // just decided to keep and shift the tail a second time.
+        mem::forget(self);
+    }
+}
+
+impl<T, A: Allocator> Drop for Drain<T, A> {
+    fn drop(&mut self) {
+        // drop any remaining unyielded elements
+        for _ in &mut self.iter {}
+
+        if self.tail_len > 0 {
             unsafe {
-                self.raw.end = self.raw.end.offset(-1);
-                Some(ptr::read(self.raw.end))
+                let source_vec = &mut *self.vec;
+                let start = source_vec.len;
+                let tail = self.tail_start;
+                if tail != start {
+                    let src = source_vec.ptr().offset(tail as isize);
+                    let dst = source_vec.ptr().offset(start as isize);
+                    ptr::copy(src, dst, self.tail_len);
+                }
+                source_vec.len = start + self.tail_len;
             }
         }
     }
 }
 
-impl<T> DoubleEndedIterator for IntoIter<T> {
-    fn next_back(&mut self) -> Option<T> {
-        if self.raw.start == self.raw.end {
+// guards the element currently under test by `pred` so it's still dropped
+// (rather than leaked) if `pred` panics; disarmed via `mem::forget` once
+// `pred` returns normally, since by then the element is either handed to
+// the caller or accounted for by the surrounding compaction.
+struct ExtractIfGuard<T>(*mut T);
+
+impl<T> Drop for ExtractIfGuard<T> {
+    fn drop(&mut self) {
+        unsafe { ptr::drop_in_place(self.0) }
+    }
+}
+
+pub struct ExtractIf<T, F: FnMut(&mut T) -> bool, A: Allocator = Global> {
+    vec: *mut Vec<T, A>,
+    // index of the next element to inspect
+    read: usize,
+    // index of the hole that retained elements get compacted into
+    write: usize,
+    old_len: usize,
+    pred: F,
+}
+
+impl<T, F: FnMut(&mut T) -> bool, A: Allocator> Iterator for ExtractIf<T, F, A> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        unsafe {
+            let vec = &mut *self.vec;
+            while self.read < self.old_len {
+                let cur = vec.ptr().offset(self.read as isize);
+                self.read += 1;
+
+                let guard = ExtractIfGuard(cur);
+                let matched = (self.pred)(&mut *cur);
+                mem::forget(guard);
+
+                if matched {
+                    return Some(ptr::read(cur));
+                }
+
+                if self.write != self.read - 1 {
+                    let hole = vec.ptr().offset(self.write as isize);
+                    ptr::copy(cur, hole, 1);
+                }
+                self.write += 1;
+            }
             None
-        } else {
-            unsafe {
-                self.raw.start = self.raw.start.offset(1);
-                Some(ptr::read(self.raw.start.offset(-1)))
+        }
+    }
+}
+
+impl<T, F: FnMut(&mut T) -> bool, A: Allocator> Drop for ExtractIf<T, F, A> {
+    fn drop(&mut self) {
+        unsafe {
+            let vec = &mut *self.vec;
+
+            // finish scanning whatever we haven't visited yet -- this runs
+            // even if the caller dropped us early or `pred` panicked, so the
+            // `Vec` is never left with a leaked or duplicated slot.
+            while self.read < self.old_len {
+                let cur = vec.ptr().offset(self.read as isize);
+                self.read += 1;
+
+                let guard = ExtractIfGuard(cur);
+                let matched = (self.pred)(&mut *cur);
+                mem::forget(guard);
+
+                if matched {
+                    ptr::drop_in_place(cur);
+                    continue;
+                }
+
+                if self.write != self.read - 1 {
+                    let hole = vec.ptr().offset(self.write as isize);
+                    ptr::copy(cur, hole, 1);
+                }
+                self.write += 1;
             }
+
+            vec.len = self.write;
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Vec;
+    use super::{AllocError, Allocator, Vec};
+    use std::alloc::{alloc, dealloc, realloc, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
 
     #[test]
     fn test() {
@@ -272,4 +769,278 @@ mod tests {
         assert_eq!(iter.next(), Some(2));
         assert_eq!(iter.next(), None);
     }
+
+    #[test]
+    fn zst() {
+        let mut v = Vec::<()>::new();
+        assert_eq!(v.pop(), None);
+
+        v.push(());
+        v.push(());
+        v.push(());
+        assert_eq!(v.len(), 3);
+        assert_eq!(v.pop(), Some(()));
+        assert_eq!(v.pop(), Some(()));
+        assert_eq!(v.pop(), Some(()));
+        assert_eq!(v.pop(), None);
+    }
+
+    #[test]
+    fn drain() {
+        let mut v = Vec::<i32>::new();
+        v.push(1);
+        v.push(2);
+        v.push(3);
+        v.push(4);
+        v.push(5);
+
+        {
+            let mut drain = v.drain(1..4);
+            assert_eq!(drain.next(), Some(4));
+            assert_eq!(drain.next_back(), Some(2));
+            assert_eq!(drain.next(), Some(3));
+            assert_eq!(drain.next(), None);
+        }
+
+        assert_eq!(v[..], [1, 5]);
+    }
+
+    #[test]
+    fn drain_keep_rest() {
+        let mut v = Vec::<i32>::new();
+        v.push(1);
+        v.push(2);
+        v.push(3);
+        v.push(4);
+        v.push(5);
+
+        {
+            let mut drain = v.drain(1..4);
+            assert_eq!(drain.next(), Some(4));
+            drain.keep_rest();
+        }
+
+        assert_eq!(v[..], [1, 2, 3, 5]);
+    }
+
+    #[test]
+    fn drain_keep_rest_zst() {
+        let mut v = Vec::<()>::new();
+        v.push(());
+        v.push(());
+        v.push(());
+
+        {
+            let mut drain = v.drain(0..2);
+            assert_eq!(drain.next(), Some(()));
+            drain.keep_rest();
+        }
+
+        assert_eq!(v.len(), 2);
+    }
+
+    #[test]
+    fn try_push() {
+        let mut v = Vec::<i32>::new();
+        assert!(v.try_push(1).is_ok());
+        assert!(v.try_push(2).is_ok());
+        assert_eq!(v[..], [1, 2]);
+    }
+
+    #[test]
+    fn try_reserve_overflow() {
+        let mut v = Vec::<i32>::new();
+        assert!(v.try_reserve(::std::usize::MAX).is_err());
+    }
+
+    #[test]
+    fn with_capacity_and_reserve() {
+        let mut v = Vec::<i32>::with_capacity(4);
+        assert_eq!(v.capacity(), 4);
+
+        v.push(1);
+        v.push(2);
+        assert_eq!(v.capacity(), 4);
+
+        v.reserve(10);
+        assert!(v.capacity() >= 12);
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut v = Vec::<i32>::with_capacity(8);
+        v.push(1);
+        v.push(2);
+        assert_eq!(v.capacity(), 8);
+
+        v.shrink_to_fit();
+        assert_eq!(v.capacity(), 2);
+        assert_eq!(v[..], [1, 2]);
+    }
+
+    #[test]
+    fn into_iter_size_hint() {
+        let mut v = Vec::<i32>::new();
+        v.push(1);
+        v.push(2);
+        v.push(3);
+
+        let mut iter = v.into_iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+
+        iter.next();
+        assert_eq!(iter.len(), 2);
+    }
+
+    #[test]
+    fn extract_if() {
+        let mut v = Vec::<i32>::new();
+        v.push(1);
+        v.push(2);
+        v.push(3);
+        v.push(4);
+        v.push(5);
+
+        let evens: std::vec::Vec<i32> = v.extract_if(|&mut x| x % 2 == 0).collect();
+
+        assert_eq!(evens, [2, 4]);
+        assert_eq!(v[..], [1, 3, 5]);
+    }
+
+    #[test]
+    fn extract_if_pred_panic_drops_every_element() {
+        struct DropCounter<'a>(&'a Cell<usize>);
+        impl<'a> Drop for DropCounter<'a> {
+            fn drop(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let dropped = Cell::new(0);
+        let mut v = Vec::new();
+        v.push(DropCounter(&dropped));
+        v.push(DropCounter(&dropped));
+        v.push(DropCounter(&dropped));
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut seen = 0;
+            v.extract_if(|_| {
+                seen += 1;
+                if seen == 2 {
+                    panic!("boom");
+                }
+                false
+            })
+            .for_each(drop);
+        }));
+
+        assert!(result.is_err());
+        drop(v);
+        assert_eq!(dropped.get(), 3);
+    }
+
+    #[test]
+    fn extract_if_partial() {
+        let mut v = Vec::<i32>::new();
+        v.push(1);
+        v.push(2);
+        v.push(3);
+        v.push(4);
+
+        {
+            let mut iter = v.extract_if(|&mut x| x % 2 == 0);
+            assert_eq!(iter.next(), Some(2));
+            // drop the iterator without exhausting it
+        }
+
+        assert_eq!(v[..], [1, 3]);
+    }
+
+    #[test]
+    fn zst_into_iter() {
+        let mut v = Vec::<()>::new();
+        v.push(());
+        v.push(());
+        v.push(());
+
+        let count = v.into_iter().count();
+        assert_eq!(count, 3);
+    }
+
+    // a minimal `Allocator` that forwards to the global allocator but counts
+    // how many times it's asked to allocate and deallocate, so tests can
+    // assert the custom allocator (rather than `Global`) was actually used,
+    // and that every allocation it hands out is eventually freed through it.
+    // `grow` resizes an existing allocation (possibly moving it) rather than
+    // adding one that needs its own deallocation, so it isn't counted here.
+    struct CountingAlloc {
+        allocations: Cell<usize>,
+        deallocations: Cell<usize>,
+    }
+
+    impl Allocator for CountingAlloc {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<u8>, AllocError> {
+            self.allocations.set(self.allocations.get() + 1);
+            unsafe { NonNull::new(alloc(layout)).ok_or(AllocError) }
+        }
+
+        unsafe fn grow(
+            &self,
+            ptr: NonNull<u8>,
+            old_layout: Layout,
+            new_layout: Layout,
+        ) -> Result<NonNull<u8>, AllocError> {
+            NonNull::new(realloc(ptr.as_ptr(), old_layout, new_layout.size())).ok_or(AllocError)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.deallocations.set(self.deallocations.get() + 1);
+            dealloc(ptr.as_ptr(), layout);
+        }
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let alloc = CountingAlloc {
+            allocations: Cell::new(0),
+            deallocations: Cell::new(0),
+        };
+
+        {
+            let mut v = Vec::new_in(&alloc);
+
+            v.push(1);
+            v.push(2);
+            v.push(3);
+
+            assert_eq!(v[..], [1, 2, 3]);
+            assert!(std::ptr::eq(*v.allocator(), &alloc));
+            // `v`'s buffer isn't freed until it's dropped below.
+        }
+
+        assert!(alloc.allocations.get() > 0);
+        assert_eq!(alloc.allocations.get(), alloc.deallocations.get());
+
+        // `into_iter` moves the backing `RawVec` into `IntoIter`; its `Drop`
+        // impl must free through the same allocator too, not leak it.
+        let mut v = Vec::new_in(&alloc);
+        v.push(4);
+        v.push(5);
+        v.into_iter().count();
+
+        assert_eq!(alloc.allocations.get(), alloc.deallocations.get());
+    }
+
+    #[test]
+    fn with_capacity_in() {
+        let alloc = CountingAlloc {
+            allocations: Cell::new(0),
+            deallocations: Cell::new(0),
+        };
+        let v = Vec::<i32, _>::with_capacity_in(4, &alloc);
+
+        assert_eq!(v.capacity(), 4);
+        assert_eq!(alloc.allocations.get(), 1);
+    }
 }